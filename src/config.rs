@@ -0,0 +1,62 @@
+use clap::Parser;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Runtime configuration, parsed from CLI flags with environment-variable
+/// fallbacks so operators can self-host without recompiling.
+#[derive(Parser)]
+#[command(author, version, about)]
+pub struct Config {
+    /// Address the HTTP server binds to.
+    #[arg(long, env = "PIXUM_BIND_ADDRESS", default_value = "0.0.0.0:3000")]
+    pub bind_address: SocketAddr,
+
+    /// Connection URL for the Redis cache.
+    #[arg(long, env = "PIXUM_REDIS_URL", default_value = "redis://redis:6379/")]
+    pub redis_url: String,
+
+    /// Timeout, in seconds, for requests made to Pixiv.
+    #[arg(long, env = "PIXUM_REQUEST_TIMEOUT_SECS", default_value_t = 10)]
+    pub request_timeout_secs: u64,
+
+    /// Number of requests allowed per rate-limit period.
+    #[arg(long, env = "PIXUM_RATE_LIMIT_REQUESTS", default_value_t = 50)]
+    pub rate_limit_requests: u64,
+
+    /// Length, in seconds, of the rate-limit period.
+    #[arg(long, env = "PIXUM_RATE_LIMIT_PERIOD_SECS", default_value_t = 10)]
+    pub rate_limit_period_secs: u64,
+
+    /// Number of requests buffered while waiting on the rate limiter.
+    #[arg(long, env = "PIXUM_BUFFER_SIZE", default_value_t = 100)]
+    pub buffer_size: usize,
+
+    /// Maximum number of requests processed concurrently.
+    #[arg(long, env = "PIXUM_CONCURRENCY_LIMIT", default_value_t = 100)]
+    pub concurrency_limit: usize,
+
+    /// TTL, in seconds, for cached original-image bytes.
+    #[arg(long, env = "PIXUM_BYTES_CACHE_TTL_SECS", default_value_t = 86400)]
+    pub bytes_cache_ttl_secs: u64,
+
+    /// Largest image, in bytes, eligible for the bytes cache; bigger images
+    /// are served without being cached so they can't crowd out the rest.
+    #[arg(
+        long,
+        env = "PIXUM_BYTES_CACHE_MAX_BYTES",
+        default_value_t = 10 * 1024 * 1024
+    )]
+    pub bytes_cache_max_bytes: usize,
+}
+
+impl Config {
+    #[must_use]
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout_secs)
+    }
+
+    #[must_use]
+    pub fn rate_limit_period(&self) -> Duration {
+        Duration::from_secs(self.rate_limit_period_secs)
+    }
+}
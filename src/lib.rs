@@ -1,45 +1,50 @@
 #![warn(clippy::all, clippy::pedantic)]
 #![forbid(unsafe_code)]
 
+mod config;
 mod routes;
+pub use config::Config;
 pub use routes::*;
 
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use deadpool_redis::{Config, Runtime, Pool};
+use deadpool_redis::{Config as RedisConfig, Pool, Runtime};
 use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::Client;
-use std::time::Duration;
 
 pub struct AppState {
     client: Client,
     pool: Pool,
+    bytes_cache_ttl_secs: u64,
+    bytes_cache_max_bytes: usize,
 }
 
 impl AppState {
+    /// # Panics
+    /// Panics if the `reqwest` client can't be built, or if the Redis
+    /// connection pool can't be created from `redis_url` (e.g. it is malformed).
     #[must_use]
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
         let mut headers = HeaderMap::new();
         headers.append("Accept-Language", HeaderValue::from_static("en"));
 
         let client = Client::builder()
             .default_headers(headers)
             .https_only(true)
-            .timeout(Duration::from_secs(10))
+            .timeout(config.request_timeout())
             .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/108.0.0.0 Safari/537.36")
             .build()
             .expect("Failed to build reqwest Client");
 
-        let config = Config::from_url("redis://redis:6379/");
-        let pool = config.create_pool(Some(Runtime::Tokio1)).expect("Failed to create database pool");
-        
-        Self { client, pool }
-    }
-}
+        let redis_config = RedisConfig::from_url(&config.redis_url);
+        let pool = redis_config.create_pool(Some(Runtime::Tokio1)).expect("Failed to create database pool");
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self::new()
+        Self {
+            client,
+            pool,
+            bytes_cache_ttl_secs: config.bytes_cache_ttl_secs,
+            bytes_cache_max_bytes: config.bytes_cache_max_bytes,
+        }
     }
 }
 
@@ -53,6 +58,8 @@ pub enum AppError {
     ServerUnreachable,
     ZeroQuery,
     TooHighQuery { max: u16 },
+    InvalidSize,
+    RangeNotSatisfiable,
     Internal,
 }
 
@@ -87,6 +94,14 @@ impl IntoResponse for AppError {
                     }
                     
                 ),
+                Self::InvalidSize => (
+                    StatusCode::BAD_REQUEST,
+                    String::from("The requested thumbnail size is invalid; valid sizes are 80, 160, 320, 640, 1080, and 2160 pixels."),
+                ),
+                Self::RangeNotSatisfiable => (
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    String::from("The requested range could not be satisfied."),
+                ),
                 Self::Internal => (
                     StatusCode::INTERNAL_SERVER_ERROR,
                     String::from("An internal server error occurred."),
@@ -4,7 +4,8 @@
 use axum::http::header::{self, HeaderName, HeaderValue};
 use axum::{error_handling::HandleErrorLayer, http::StatusCode, routing::get, Router, Server};
 use axum_extra::routing::RouterExt;
-use pixum::{work, AppState};
+use clap::Parser;
+use pixum::{work, AppState, Config};
 use std::{sync::Arc, time::Duration};
 use tower::ServiceBuilder;
 use tower_http::ServiceBuilderExt;
@@ -19,7 +20,14 @@ async fn fallback() -> (StatusCode, String) {
 
 #[tokio::main]
 async fn main() {
-    let shared_state = Arc::new(AppState::new());
+    let config = Config::parse();
+    let bind_address = config.bind_address;
+    let rate_limit_requests = config.rate_limit_requests;
+    let rate_limit_period = config.rate_limit_period();
+    let buffer_size = config.buffer_size;
+    let concurrency_limit = config.concurrency_limit;
+
+    let shared_state = Arc::new(AppState::new(&config));
 
     let app = Router::new()
         .fallback(fallback)
@@ -32,6 +40,10 @@ async fn main() {
             "/:work_id/:index",
             get(work::source).with_state(shared_state.clone()),
         )
+        .route_with_tsr(
+            "/:work_id/:index/thumbnail/:size",
+            get(work::thumbnail).with_state(shared_state.clone()),
+        )
         .with_state(shared_state)
         .layer(
             ServiceBuilder::new()
@@ -65,13 +77,13 @@ async fn main() {
                 // Tower's rate-limiting middleware does not implement Clone
                 // (required by HandleErrorLayer)
                 // so a buffer middleware is also used.
-                .buffer(100)
-                .rate_limit(50, Duration::from_secs(10))
+                .buffer(buffer_size)
+                .rate_limit(rate_limit_requests, rate_limit_period)
                 .timeout(Duration::from_secs(15))
-                .concurrency_limit(100),
+                .concurrency_limit(concurrency_limit),
         );
 
-    Server::bind(&"0.0.0.0:3000".parse().unwrap())
+    Server::bind(&bind_address)
         .serve(app.into_make_service())
         .await
         .unwrap();
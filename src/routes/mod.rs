@@ -0,0 +1,2 @@
+pub mod work;
+mod ugoira;
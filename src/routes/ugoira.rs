@@ -0,0 +1,214 @@
+use crate::{AppError, AppResult};
+use reqwest::Client;
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::process::Stdio;
+use tokio::process::Command;
+use zip::ZipArchive;
+
+#[derive(Deserialize)]
+pub(super) struct UgoiraFrame {
+    file: String,
+    delay: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UgoiraMetaBody {
+    original_src: String,
+    frames: Vec<UgoiraFrame>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UgoiraBodyData {
+    Error(Vec<()>),
+    Success(UgoiraMetaBody),
+}
+
+#[derive(Deserialize)]
+struct UgoiraMetaResponse {
+    error: bool,
+    body: UgoiraBodyData,
+}
+
+/// Fetches the frame archive URL and per-frame delays for a ugoira (animated)
+/// work. Returns `Ok(None)` when the work isn't a ugoira rather than an error,
+/// since this is also used as the existence check for the format.
+pub(super) async fn fetch_ugoira_meta(
+    client: &Client,
+    work_id: u32,
+) -> AppResult<Option<(String, Vec<UgoiraFrame>)>> {
+    let response: UgoiraMetaResponse = client
+        .get(format!(
+            "https://www.pixiv.net/ajax/illust/{work_id}/ugoira_meta"
+        ))
+        .send()
+        .await
+        .map_err(|_| AppError::Internal)?
+        .json()
+        .await
+        .map_err(|_| AppError::ServerUnreachable)?;
+
+    if response.error {
+        return Ok(None);
+    }
+
+    match response.body {
+        UgoiraBodyData::Success(data) => Ok(Some((data.original_src, data.frames))),
+        // `Vec<()>` (rather than `()`) is deliberate: Pixiv's error body is a
+        // JSON array (`[]`), and serde's untagged dispatch only matches `()`
+        // against `null`, not an array — so this is what actually parses.
+        // Destructuring it (instead of `Error(_)`) keeps the field read so
+        // rustc's `dead_code` lint doesn't fire under `-D warnings`.
+        UgoiraBodyData::Error(errors) => {
+            debug_assert!(errors.is_empty(), "pixiv's ugoira error body is expected to be empty");
+            Ok(None)
+        }
+    }
+}
+
+/// Rejects a frame filename (as reported by Pixiv's `ugoira_meta` endpoint,
+/// so not to be trusted) that isn't a bare filename — a `..`/path-separator
+/// could escape the temp directory it's extracted into, and a `'`/line
+/// break could inject extra directives into the ffmpeg concat script.
+fn sanitize_frame_name(file: &str) -> AppResult<&str> {
+    let is_safe = !file.is_empty()
+        && !file.contains(['/', '\\', '\'', '\n', '\r'])
+        && file != "..";
+
+    is_safe.then_some(file).ok_or(AppError::Internal)
+}
+
+/// Downloads the zipped frames, unpacks them, and feeds them to `ffmpeg`
+/// (using the original per-frame delays) to produce an MP4.
+pub(super) async fn transcode_ugoira(
+    client: &Client,
+    work_id: u32,
+    zip_url: &str,
+    frames: &[UgoiraFrame],
+) -> AppResult<Vec<u8>> {
+    let referer_string =
+        format!("https://www.pixiv.net/member_illust.php?mode=medium&illust_id={work_id}");
+
+    let zip_bytes = client
+        .get(zip_url)
+        .header("Referer", &referer_string)
+        .send()
+        .await
+        .map_err(|_| AppError::Internal)?
+        .bytes()
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+    let mut archive =
+        ZipArchive::new(Cursor::new(zip_bytes)).map_err(|_| AppError::Internal)?;
+
+    let temp_dir = tempfile::tempdir().map_err(|_| AppError::Internal)?;
+
+    let mut concat_script = String::new();
+    for frame in frames {
+        let file_name = sanitize_frame_name(&frame.file)?;
+
+        let mut entry = archive
+            .by_name(file_name)
+            .map_err(|_| AppError::Internal)?;
+        let frame_path = temp_dir.path().join(file_name);
+        let mut out_file = std::fs::File::create(&frame_path).map_err(|_| AppError::Internal)?;
+        std::io::copy(&mut entry, &mut out_file).map_err(|_| AppError::Internal)?;
+
+        let duration_seconds = f64::from(frame.delay) / 1000.0;
+        let _ = writeln!(concat_script, "file '{file_name}'\nduration {duration_seconds}");
+    }
+
+    let concat_path = temp_dir.path().join("frames.txt");
+    tokio::fs::write(&concat_path, concat_script)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+    let output_path = temp_dir.path().join("out.mp4");
+
+    let status = Command::new("ffmpeg")
+        .arg("-y")
+        .args(["-f", "concat", "-safe", "0", "-i"])
+        .arg(&concat_path)
+        .args(["-vsync", "vfr", "-pix_fmt", "yuv420p"])
+        .arg(&output_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+    if !status.success() {
+        return Err(AppError::Internal);
+    }
+
+    tokio::fs::read(&output_path)
+        .await
+        .map_err(|_| AppError::Internal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ugoira_meta_response_parses_a_successful_body() {
+        let json = r#"{
+            "error": false,
+            "body": {
+                "originalSrc": "https://example.com/frames.zip",
+                "frames": [
+                    { "file": "000000.jpg", "delay": 100 },
+                    { "file": "000001.jpg", "delay": 120 }
+                ]
+            }
+        }"#;
+
+        let response: UgoiraMetaResponse = serde_json::from_str(json).unwrap();
+        assert!(!response.error);
+        let UgoiraBodyData::Success(body) = response.body else {
+            panic!("expected a successful ugoira meta body");
+        };
+        assert_eq!(body.original_src, "https://example.com/frames.zip");
+        assert_eq!(body.frames.len(), 2);
+        assert_eq!(body.frames[0].file, "000000.jpg");
+        assert_eq!(body.frames[0].delay, 100);
+    }
+
+    #[test]
+    fn ugoira_meta_response_parses_an_error_body() {
+        let json = r#"{ "error": true, "body": [] }"#;
+
+        let response: UgoiraMetaResponse = serde_json::from_str(json).unwrap();
+        assert!(response.error);
+        assert!(matches!(response.body, UgoiraBodyData::Error(_)));
+    }
+
+    #[test]
+    fn sanitize_frame_name_accepts_an_ordinary_filename() {
+        assert_eq!(sanitize_frame_name("000000.jpg").ok(), Some("000000.jpg"));
+    }
+
+    #[test]
+    fn sanitize_frame_name_rejects_path_traversal() {
+        assert!(sanitize_frame_name("..").is_err());
+        assert!(sanitize_frame_name("../../etc/passwd").is_err());
+        assert!(sanitize_frame_name("subdir/frame.jpg").is_err());
+        assert!(sanitize_frame_name("subdir\\frame.jpg").is_err());
+    }
+
+    #[test]
+    fn sanitize_frame_name_rejects_concat_script_injection() {
+        assert!(sanitize_frame_name("a'\nfile 'b").is_err());
+        assert!(sanitize_frame_name("a\rb").is_err());
+    }
+
+    #[test]
+    fn sanitize_frame_name_rejects_an_empty_name() {
+        assert!(sanitize_frame_name("").is_err());
+    }
+}
@@ -1,21 +1,31 @@
+use super::ugoira;
 use crate::{AppError, AppResult, AppState};
 use ahash::HashMap;
+use axum::body::StreamBody;
 use axum::http::header::{self, HeaderValue};
+use axum::http::HeaderMap;
 use axum::{
     extract::{rejection::PathRejection, Path, State},
     response::{IntoResponse, Response as AxumResponse},
     Json,
 };
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use deadpool_redis::{redis::Cmd, Connection};
 use futures::future::join_all;
+use image::imageops::FilterType;
 use mime_guess::Mime;
 use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
 use std::{path::Path as StdPath, sync::Arc};
 
 type PathResult<T> = Result<Path<T>, PathRejection>;
 
+/// The only thumbnail sizes (longest edge, in pixels) that can be requested.
+const VALID_THUMBNAIL_SIZES: [u16; 6] = [80, 160, 320, 640, 1080, 2160];
+
 #[derive(Deserialize)]
 struct OtherWorkInfo {
     url: String,
@@ -61,14 +71,17 @@ pub struct InfoResponse {
     title: String,
     upload_time: String,
     length: u16,
+    blur_hash: String,
 }
 
 /// # Errors
 /// This function fails if:
 /// - `work_id` is invalid
+/// - Database connection fails
 /// - HTTP request to Pixiv's API fails
 /// - Server returns an HTTP error
 /// - Data of the work is unavailable
+/// - The work's first image cannot be decoded
 pub async fn info(
     work_id: PathResult<u32>,
     State(state): State<Arc<AppState>>,
@@ -76,6 +89,11 @@ pub async fn info(
     if let Ok(id) = work_id {
         let data = fetch_work_info(&state.client, id.0).await?;
 
+        // The application can work without a database, but a connection error
+        // indicates something is wrong, so the server will immediately return an error.
+        let mut connection = state.pool.get().await.map_err(|_| AppError::Internal)?;
+        let blur_hash = get_blurhash(&state.client, &mut connection, id.0).await?;
+
         let mut response = Json(InfoResponse {
             artist_name: data.user_name,
             artist_id: data.user_id.parse().ok(),
@@ -84,6 +102,7 @@ pub async fn info(
             upload_time: data.upload_date,
             // The value of num_entries is 1 more than the actual number of images
             length: data.num_entries - 1,
+            blur_hash,
         })
         .into_response();
 
@@ -102,6 +121,114 @@ pub async fn info(
     }
 }
 
+/// Computes (and caches) a `BlurHash` placeholder for a work's first image, so
+/// front-ends have something to paint before the full image has loaded.
+async fn get_blurhash(
+    client: &Client,
+    connection: &mut Connection,
+    work_id: u32,
+) -> AppResult<String> {
+    let cache_entry_name = format!("{work_id}_blurhash");
+
+    if let Ok(hash) = Cmd::get(&cache_entry_name)
+        .query_async::<_, String>(connection)
+        .await
+    {
+        return Ok(hash);
+    }
+
+    let (_, image_data) = fetch_original_image(client, connection, work_id, 1).await?;
+
+    let format = image::guess_format(&image_data).map_err(|_| AppError::Internal)?;
+
+    // A BlurHash only encodes a handful of DCT components, so hashing a small
+    // downscaled copy is both much faster and visually indistinguishable from
+    // hashing the full-resolution original.
+    let image = image::load_from_memory_with_format(&image_data, format)
+        .map_err(|_| AppError::Internal)?
+        .resize(128, 128, FilterType::Triangle)
+        .to_rgba8();
+
+    let hash = blurhash::encode(4, 3, image.width(), image.height(), &image);
+
+    #[allow(unused_must_use)]
+    {
+        Cmd::set(&cache_entry_name, &hash)
+            .query_async::<_, ()>(connection)
+            .await;
+    }
+
+    Ok(hash)
+}
+
+/// Validators used to let clients revalidate a cached response instead of
+/// re-downloading it: a strong `ETag` derived from the identity of the image,
+/// and an optional `Last-Modified` taken from the work's `upload_date`.
+struct Validators {
+    etag: String,
+    last_modified: Option<HeaderValue>,
+}
+
+impl Validators {
+    fn new(work_id: u32, index: u16, url: &str, upload_date: Option<&str>) -> Self {
+        let mut hasher = ahash::AHasher::default();
+        (work_id, index, url).hash(&mut hasher);
+        let etag = format!("\"{:x}\"", hasher.finish());
+
+        let last_modified = upload_date
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+            .and_then(|date| {
+                let formatted = date
+                    .with_timezone(&Utc)
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string();
+                HeaderValue::from_str(&formatted).ok()
+            });
+
+        Self {
+            etag,
+            last_modified,
+        }
+    }
+
+    /// Checks whether a request's `If-None-Match`/`If-Modified-Since` headers
+    /// indicate the client's cached copy is still fresh.
+    fn satisfied_by(&self, request_headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = request_headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+        {
+            return if_none_match
+                .split(',')
+                .any(|tag| tag.trim() == self.etag || tag.trim() == "*");
+        }
+
+        if let (Some(if_modified_since), Some(last_modified)) = (
+            request_headers.get(header::IF_MODIFIED_SINCE),
+            &self.last_modified,
+        ) {
+            return if_modified_since == last_modified;
+        }
+
+        false
+    }
+
+    fn apply_to(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, value);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            headers.insert(header::LAST_MODIFIED, last_modified.clone());
+        }
+    }
+}
+
+fn not_modified_response(validators: &Validators) -> AxumResponse {
+    let mut headers = HeaderMap::new();
+    validators.apply_to(&mut headers);
+    (StatusCode::NOT_MODIFIED, headers).into_response()
+}
+
 /// # Errors
 /// This function fails if:
 /// - `work_id` or `index` are invalid
@@ -111,13 +238,23 @@ pub async fn info(
 /// - Data of the work is unavailable
 pub async fn source(
     work_info: PathResult<(u32, u16)>,
+    request_headers: HeaderMap,
     State(state): State<Arc<AppState>>,
-) -> AppResult<impl IntoResponse> {
+) -> AppResult<AxumResponse> {
     if let Ok(Path((work_id, index))) = work_info {
         // The application can work without a database, but a connection error
         // indicates something is wrong, so the server will immediately return an error.
         let mut connection = state.pool.get().await.map_err(|_| AppError::Internal)?;
-        get_image_data(&state.client, &mut connection, work_id, index).await
+        get_image_data(
+            &state.client,
+            &mut connection,
+            work_id,
+            index,
+            &request_headers,
+            state.bytes_cache_ttl_secs,
+            state.bytes_cache_max_bytes,
+        )
+        .await
     } else {
         Err(AppError::InvalidUrl)
     }
@@ -128,13 +265,331 @@ async fn get_image_data(
     connection: &mut Connection,
     work_id: u32,
     index: u16,
-) -> AppResult<impl IntoResponse> {
+    request_headers: &HeaderMap,
+    bytes_cache_ttl_secs: u64,
+    bytes_cache_max_bytes: usize,
+) -> AppResult<AxumResponse> {
+    // Ugoira (animated) works have no ordinary original-image URL; they're
+    // transcoded separately and served in full regardless of `index`.
+    if let Some(video) = get_ugoira_data(client, connection, work_id).await? {
+        return Ok(video);
+    }
+
+    // Revalidate against whatever is already cached before contacting Pixiv at all;
+    // this is the common case for a client refreshing a page it already has open.
+    let cached_validators = try_cached_validators(connection, work_id, index).await;
+    if let Some(validators) = &cached_validators {
+        if validators.satisfied_by(request_headers) {
+            return Ok(not_modified_response(validators));
+        }
+    }
+
+    let range = request_headers.get(header::RANGE);
+
+    // A request for an image whose bytes are already cached — Range or
+    // not — is served from the bytes cache without contacting Pixiv,
+    // since the cache holds the complete original bytes to slice locally.
+    if let Some((file_name, mime_type, data)) =
+        try_cached_image_bytes(connection, work_id, index).await
+    {
+        let validators = cached_validators
+            .unwrap_or_else(|| Validators::new(work_id, index, &file_name, None));
+        return respond_with_range(&file_name, &mime_type, &data, range, &validators);
+    }
+
+    let (source, response) = fetch_original_image_stream(client, connection, work_id, index).await?;
+    let validators = Validators::new(work_id, index, &source.url, source.upload_date.as_deref());
+
+    if validators.satisfied_by(request_headers) {
+        return Ok(not_modified_response(&validators));
+    }
+
+    let content_length = response.content_length();
+
+    // A Range request has to be satisfied by slicing bytes we hold locally,
+    // so buffering is unavoidable there; a plain request is only worth
+    // buffering when the image is small enough to populate the bytes cache,
+    // otherwise it streams straight through to keep a large first-time fetch
+    // off the heap.
+    let fits_cache = content_length.is_some_and(|len| fits_in_cache(len, bytes_cache_max_bytes));
+    if range.is_some() || fits_cache {
+        let image_data = response.bytes().await.map_err(|_| AppError::Internal)?;
+        cache_image_bytes(
+            connection,
+            work_id,
+            index,
+            &image_data,
+            bytes_cache_ttl_secs,
+            bytes_cache_max_bytes,
+        )
+        .await;
+
+        return respond_with_range(
+            &source.file_name,
+            &source.mime_type,
+            &image_data,
+            range,
+            &validators,
+        );
+    }
+
+    let headers = generate_http_headers(
+        &source.file_name,
+        &source.mime_type,
+        content_length,
+        Some(&validators),
+    );
+    let body = StreamBody::new(response.bytes_stream());
+
+    Ok((StatusCode::OK, headers, body).into_response())
+}
+
+/// Parses a single-range `Range: bytes=start-end` header (including the
+/// suffix `bytes=-N` form) against a resource of `total_len` bytes, returning
+/// the inclusive `(start, end)` byte offsets. An end past the last byte (or a
+/// suffix longer than the resource) is clamped to the resource. Anything
+/// else — multiple ranges, a malformed header, a backwards range, or a start
+/// at or beyond the end of the resource — is treated as unsatisfiable.
+fn parse_range(range: &HeaderValue, total_len: u64) -> Option<(u64, u64)> {
+    let spec = range.to_str().ok()?.strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+
+    let (start, end) = if start.is_empty() {
+        let suffix_len: u64 = end.parse().ok()?;
+        let suffix_len = suffix_len.min(total_len);
+        (total_len.checked_sub(suffix_len)?, total_len.checked_sub(1)?)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            total_len.checked_sub(1)?
+        } else {
+            end.parse::<u64>().ok()?.min(total_len.checked_sub(1)?)
+        };
+        (start, end)
+    };
+
+    (start <= end && start < total_len).then_some((start, end))
+}
+
+/// Builds the final `source` response from fully-resolved bytes, slicing out
+/// whatever a `Range` header asks for (`206`, or `416` if it can't be
+/// satisfied) and serving the whole image otherwise (`200`).
+fn respond_with_range(
+    file_name: &str,
+    mime_type: &Mime,
+    data: &Bytes,
+    range: Option<&HeaderValue>,
+    validators: &Validators,
+) -> AppResult<AxumResponse> {
+    let total_len = data.len() as u64;
+
+    let (status, content_range, body) = match range {
+        Some(range) => {
+            let (start, end) = parse_range(range, total_len).ok_or(AppError::RangeNotSatisfiable)?;
+            let content_range = format!("bytes {start}-{end}/{total_len}");
+            // `start`/`end` are already bounds-checked against `total_len`, which
+            // itself came from `data.len()` (a `usize`), so this conversion back
+            // can't actually fail — it's just not a `u64 as usize` cast.
+            let start = usize::try_from(start).map_err(|_| AppError::RangeNotSatisfiable)?;
+            let end = usize::try_from(end).map_err(|_| AppError::RangeNotSatisfiable)?;
+            let body = data.slice(start..=end);
+            (StatusCode::PARTIAL_CONTENT, Some(content_range), body)
+        }
+        None => (StatusCode::OK, None, data.clone()),
+    };
+
+    let content_length = Some(body.len() as u64);
+    let mut headers = generate_http_headers(file_name, mime_type, content_length, Some(validators));
+    if let Some(content_range) = content_range.and_then(|value| HeaderValue::from_str(&value).ok())
+    {
+        headers.insert(header::CONTENT_RANGE, content_range);
+    }
+
+    Ok((status, headers, body).into_response())
+}
+
+/// Serves a ugoira's transcoded animation, producing and caching it on a
+/// miss. Returns `Ok(None)` when `work_id` isn't a ugoira work at all, so the
+/// caller can fall back to the ordinary image path.
+async fn get_ugoira_data(
+    client: &Client,
+    connection: &mut Connection,
+    work_id: u32,
+) -> AppResult<Option<AxumResponse>> {
+    let cache_entry_name = format!("{work_id}_ugoira");
+
+    if let Ok(video) = Cmd::get(&cache_entry_name)
+        .query_async::<_, Vec<u8>>(connection)
+        .await
+    {
+        return Ok(Some(ugoira_response(work_id, video)));
+    }
+
+    // Whether a work is a ugoira at all never changes, so a negative result
+    // is cached just as permanently as a positive one — otherwise every
+    // request for an ordinary image would re-check this endpoint forever.
+    let not_ugoira_entry_name = format!("{work_id}_not_ugoira");
+    if Cmd::get(&not_ugoira_entry_name)
+        .query_async::<_, bool>(connection)
+        .await
+        .is_ok()
+    {
+        return Ok(None);
+    }
+
+    let Some((zip_url, frames)) = ugoira::fetch_ugoira_meta(client, work_id).await? else {
+        #[allow(unused_must_use)]
+        {
+            Cmd::set(&not_ugoira_entry_name, true)
+                .query_async::<_, ()>(connection)
+                .await;
+        }
+
+        return Ok(None);
+    };
+
+    let video = ugoira::transcode_ugoira(client, work_id, &zip_url, &frames).await?;
+
+    #[allow(unused_must_use)]
+    {
+        Cmd::set(&cache_entry_name, &video)
+            .query_async::<_, ()>(connection)
+            .await;
+    }
+
+    Ok(Some(ugoira_response(work_id, video)))
+}
+
+fn ugoira_response(work_id: u32, video: Vec<u8>) -> AxumResponse {
+    let file_name = format!("{work_id}_ugoira.mp4");
+    let mime_type = mime_guess::from_ext("mp4").first_or_octet_stream();
+    let content_length = Some(video.len() as u64);
+
+    let headers = generate_http_headers(&file_name, &mime_type, content_length, None);
+
+    (headers, Bytes::from(video)).into_response()
+}
+
+/// Looks up the URL and upload date already cached for an image, without
+/// making any request to Pixiv. Returns `None` on a cache miss.
+async fn try_cached_validators(
+    connection: &mut Connection,
+    work_id: u32,
+    index: u16,
+) -> Option<Validators> {
+    let url = Cmd::get(format!("{work_id}_{index}"))
+        .query_async::<_, String>(connection)
+        .await
+        .ok()?;
+    let upload_date = Cmd::get(format!("{work_id}_{index}_date"))
+        .query_async::<_, String>(connection)
+        .await
+        .ok();
+
+    Some(Validators::new(
+        work_id,
+        index,
+        &url,
+        upload_date.as_deref(),
+    ))
+}
+
+/// Looks up an original image's bytes from the cache, without making any
+/// request to Pixiv. Returns `None` on a cache miss.
+async fn try_cached_image_bytes(
+    connection: &mut Connection,
+    work_id: u32,
+    index: u16,
+) -> Option<(String, Mime, Bytes)> {
+    let data = Cmd::get(format!("{work_id}_{index}_bytes"))
+        .query_async::<_, Vec<u8>>(connection)
+        .await
+        .ok()?;
+
+    let format = image::guess_format(&data).ok()?;
+    let mime_type = format_to_mime(format);
+    let file_name = format!("{work_id}_p{}", index - 1);
+
+    Some((file_name, mime_type, Bytes::from(data)))
+}
+
+/// Whether an image of `len` bytes is small enough to be worth keeping in
+/// the bytes cache, versus crowding out everything else in it.
+fn fits_in_cache(len: u64, max_bytes: usize) -> bool {
+    len <= max_bytes as u64
+}
+
+/// Clamps a TTL in seconds to what `redis::Commands::set_ex` accepts, since
+/// `Config::bytes_cache_ttl_secs` is a `u64` but Redis expects a `usize`.
+fn clamp_ttl_secs(ttl_secs: u64) -> usize {
+    usize::try_from(ttl_secs).unwrap_or(usize::MAX)
+}
+
+/// Caches an original image's bytes with a TTL so later requests can skip
+/// Pixiv entirely. Images larger than `max_bytes` are left uncached so a
+/// handful of huge originals can't crowd out everything else.
+async fn cache_image_bytes(
+    connection: &mut Connection,
+    work_id: u32,
+    index: u16,
+    data: &[u8],
+    ttl_secs: u64,
+    max_bytes: usize,
+) {
+    if !fits_in_cache(data.len() as u64, max_bytes) {
+        return;
+    }
+
+    let ttl_secs = clamp_ttl_secs(ttl_secs);
+
+    #[allow(unused_must_use)]
+    {
+        Cmd::set_ex(format!("{work_id}_{index}_bytes"), data, ttl_secs)
+            .query_async::<_, ()>(connection)
+            .await;
+    }
+}
+
+/// Metadata describing where an image's bytes came from, needed to build the
+/// response headers (filename, MIME type, caching validators).
+struct ImageSource {
+    file_name: String,
+    mime_type: Mime,
+    url: String,
+    upload_date: Option<String>,
+}
+
+/// Fetches the full, original-resolution bytes of an image, consulting (and
+/// populating) the URL cache along the way. This is the shared basis for the
+/// resizing done for thumbnails and for the `BlurHash` placeholder.
+async fn fetch_original_image(
+    client: &Client,
+    connection: &mut Connection,
+    work_id: u32,
+    index: u16,
+) -> AppResult<(ImageSource, Bytes)> {
+    let (source, response) = fetch_original_image_stream(client, connection, work_id, index).await?;
+
+    let image_data = response.bytes().await.map_err(|_| AppError::Internal)?;
+
+    Ok((source, image_data))
+}
+
+/// Same as [`fetch_original_image`], but leaves the upstream response body
+/// unconsumed so callers that don't need the whole image in memory (namely
+/// the default `source` route) can stream it straight through instead.
+async fn fetch_original_image_stream(
+    client: &Client,
+    connection: &mut Connection,
+    work_id: u32,
+    index: u16,
+) -> AppResult<(ImageSource, Response)> {
     if index == 0 {
         return Err(AppError::ZeroQuery);
     }
 
     let file_name;
-    let image_data;
+    let response;
     let mime_type;
 
     {
@@ -145,16 +600,31 @@ async fn get_image_data(
             .query_async::<_, String>(connection)
             .await
         {
-            return match fetch_image_data(client, connection, &url, work_id, index, true, false)
+            return match fetch_image_response(client, connection, &url, work_id, index, true, false)
                 .await
             {
-                Ok((file_name, image_data)) => {
-                    mime_type = mime_guess::from_path(url).first_or_octet_stream();
-                    Ok((generate_http_headers(&file_name, &mime_type), image_data))
+                Ok((file_name, response)) => {
+                    let mime_type = mime_guess::from_path(&url).first_or_octet_stream();
+                    let upload_date = Cmd::get(format!("{cache_entry_name}_date"))
+                        .query_async::<_, String>(connection)
+                        .await
+                        .ok();
+                    Ok((
+                        ImageSource {
+                            file_name,
+                            mime_type,
+                            url,
+                            upload_date,
+                        },
+                        response,
+                    ))
                 }
                 Err(err) => {
                     #[allow(unused_must_use)]
                     if let AppError::WrongArtworkUrl = err {
+                        Cmd::unlink(format!("{cache_entry_name}_bytes"))
+                            .query_async::<_, ()>(connection)
+                            .await;
                         Cmd::unlink(cache_entry_name)
                             .query_async::<_, ()>(connection)
                             .await;
@@ -174,10 +644,25 @@ async fn get_image_data(
         });
     }
 
+    let upload_date = Some(data.upload_date.clone());
+
+    #[allow(unused_must_use)]
+    {
+        Cmd::set(
+            format!("{work_id}_{index}_date"),
+            &data.upload_date,
+        )
+        .query_async::<_, ()>(connection)
+        .await;
+    }
+
+    let url;
+
     if let Some(link) = data.urls.original {
-        (file_name, image_data) =
-            fetch_image_data(client, connection, &link, work_id, index, true, true).await?;
-        mime_type = mime_guess::from_path(link).first_or_octet_stream();
+        (file_name, response) =
+            fetch_image_response(client, connection, &link, work_id, index, true, true).await?;
+        mime_type = mime_guess::from_path(&link).first_or_octet_stream();
+        url = link;
     } else {
         // Original image URLs on Pixiv follow a certain pattern.
         // If the master/thumbnail image URL is present, the original link can be obtained.
@@ -193,20 +678,109 @@ async fn get_image_data(
             .replace("_square1200", "")
             .replace("_custom1200", "");
 
-        (file_name, image_data) = fetch_image_data(
-            client,
-            connection,
-            &target_link,
-            work_id,
-            index,
-            false,
-            true,
-        )
-        .await?;
-        mime_type = mime_guess::from_path(target_link).first_or_octet_stream();
+        (file_name, response) =
+            fetch_image_response(client, connection, &target_link, work_id, index, false, true)
+                .await?;
+        mime_type = mime_guess::from_path(&target_link).first_or_octet_stream();
+        url = response.url().as_str().to_string();
+    }
+
+    Ok((
+        ImageSource {
+            file_name,
+            mime_type,
+            url,
+            upload_date,
+        },
+        response,
+    ))
+}
+
+/// # Errors
+/// This function fails if:
+/// - `work_id`, `index` or `size` are invalid
+/// - Database connection fails
+/// - HTTP request to Pixiv's API fails
+/// - Server returns an HTTP error
+/// - Data of the work is unavailable
+/// - The fetched image cannot be decoded or re-encoded
+pub async fn thumbnail(
+    work_info: PathResult<(u32, u16, u16)>,
+    State(state): State<Arc<AppState>>,
+) -> AppResult<impl IntoResponse> {
+    if let Ok(Path((work_id, index, size))) = work_info {
+        if !VALID_THUMBNAIL_SIZES.contains(&size) {
+            return Err(AppError::InvalidSize);
+        }
+
+        let mut connection = state.pool.get().await.map_err(|_| AppError::Internal)?;
+        get_thumbnail_data(&state.client, &mut connection, work_id, index, size).await
+    } else {
+        Err(AppError::InvalidUrl)
+    }
+}
+
+async fn get_thumbnail_data(
+    client: &Client,
+    connection: &mut Connection,
+    work_id: u32,
+    index: u16,
+    size: u16,
+) -> AppResult<impl IntoResponse> {
+    let cache_entry_name = format!("{work_id}_{index}_{size}");
+
+    // Checks if a resized version of this image is already cached
+    if let Ok(cached) = Cmd::get(&cache_entry_name)
+        .query_async::<_, Vec<u8>>(connection)
+        .await
+    {
+        let format = image::guess_format(&cached).map_err(|_| AppError::Internal)?;
+        let mime_type = format_to_mime(format);
+        let file_name = format!("{work_id}_p{}_{size}", index - 1);
+        let content_length = Some(cached.len() as u64);
+        return Ok((
+            generate_http_headers(&file_name, &mime_type, content_length, None),
+            Bytes::from(cached),
+        ));
+    }
+
+    let (_, original_data) = fetch_original_image(client, connection, work_id, index).await?;
+
+    let format = image::guess_format(&original_data).map_err(|_| AppError::Internal)?;
+    let image = image::load_from_memory_with_format(&original_data, format)
+        .map_err(|_| AppError::Internal)?
+        .resize(u32::from(size), u32::from(size), FilterType::Lanczos3);
+
+    let mut resized_data = Cursor::new(Vec::new());
+    image
+        .write_to(&mut resized_data, format)
+        .map_err(|_| AppError::Internal)?;
+    let resized_data = resized_data.into_inner();
+
+    #[allow(unused_must_use)]
+    {
+        Cmd::set(&cache_entry_name, &resized_data)
+            .query_async::<_, ()>(connection)
+            .await;
     }
 
-    Ok((generate_http_headers(&file_name, &mime_type), image_data))
+    let mime_type = format_to_mime(format);
+    let file_name = format!("{work_id}_p{}_{size}", index - 1);
+    let content_length = Some(resized_data.len() as u64);
+
+    Ok((
+        generate_http_headers(&file_name, &mime_type, content_length, None),
+        Bytes::from(resized_data),
+    ))
+}
+
+fn format_to_mime(format: image::ImageFormat) -> Mime {
+    format
+        .extensions_str()
+        .first()
+        .map_or(mime_guess::mime::APPLICATION_OCTET_STREAM, |ext| {
+            mime_guess::from_ext(ext).first_or_octet_stream()
+        })
 }
 
 async fn fetch_work_info(client: &Client, work_id: u32) -> AppResult<WorkInfo> {
@@ -236,7 +810,11 @@ fn get_image_name_from_url(url: &str, fallback: String) -> String {
         .map_or_else(|| fallback, ToString::to_string)
 }
 
-async fn fetch_image_data(
+/// Resolves the given URL (or, if its extension isn't known yet, one of the
+/// three extensions Pixiv uses) to a live upstream response, updating the
+/// resolved-URL cache as a side effect. The response body is left untouched
+/// so callers can either stream it or buffer it as needed.
+async fn fetch_image_response(
     client: &Client,
     connection: &mut Connection,
     url: &str,
@@ -244,30 +822,24 @@ async fn fetch_image_data(
     index: u16,
     url_known: bool,
     update_cache: bool,
-) -> AppResult<(String, Bytes)> {
+) -> AppResult<(String, Response)> {
     let referer_string =
         format!("https://www.pixiv.net/member_illust.php?mode=medium&illust_id={work_id}");
 
     if url_known {
-        if let Ok(data) = fetch_image(client, url.to_string(), &referer_string)
-            .await
-            .map_err(|_| AppError::Internal)?
-            .bytes()
-            .await
-        {
-            #[allow(unused_must_use)]
-            if update_cache {
-                Cmd::set(format!("{work_id}_{index}"), url)
-                    .query_async::<_, ()>(connection)
-                    .await;
-            }
-
-            return Ok((
-                get_image_name_from_url(url, format!("{work_id}_p{}", index - 1)),
-                data,
-            ));
+        let response = fetch_image(client, url.to_string(), &referer_string).await?;
+
+        #[allow(unused_must_use)]
+        if update_cache {
+            Cmd::set(format!("{work_id}_{index}"), url)
+                .query_async::<_, ()>(connection)
+                .await;
         }
-        return Err(AppError::Internal);
+
+        return Ok((
+            get_image_name_from_url(url, format!("{work_id}_p{}", index - 1)),
+            response,
+        ));
     }
 
     // Only the link for the first image in a collection is given,
@@ -294,7 +866,6 @@ async fn fetch_image_data(
     .find_map(Result::ok)
     {
         let link = response.url().as_str().to_string();
-        let data = response.bytes().await.map_err(|_| AppError::Internal)?;
 
         #[allow(unused_must_use)]
         {
@@ -305,7 +876,7 @@ async fn fetch_image_data(
 
         Ok((
             get_image_name_from_url(&link, format!("{work_id}_p{}", index - 1)),
-            data,
+            response,
         ))
     } else {
         Err(AppError::ArtworkUnavailable)
@@ -328,16 +899,181 @@ async fn fetch_image(client: &Client, url: String, referer: &str) -> AppResult<R
     }
 }
 
-fn generate_http_headers(filename: &str, mime: &Mime) -> [(header::HeaderName, String); 3] {
-    [
-        (
-            header::CONTENT_DISPOSITION,
-            format!(r#"inline; filename="{filename}""#),
-        ),
-        (header::CONTENT_TYPE, mime.to_string()),
-        (
-            header::CACHE_CONTROL,
-            String::from("max-age=31536000, public, immutable, no-transform"),
-        ),
-    ]
+fn generate_http_headers(
+    filename: &str,
+    mime: &Mime,
+    content_length: Option<u64>,
+    validators: Option<&Validators>,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!(r#"inline; filename="{filename}""#))
+            .unwrap_or_else(|_| HeaderValue::from_static("inline")),
+    );
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(mime.as_ref())
+            .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream")),
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("max-age=31536000, public, immutable, no-transform"),
+    );
+    headers.insert(
+        header::ACCEPT_RANGES,
+        HeaderValue::from_static("bytes"),
+    );
+
+    if let Some(length) = content_length {
+        if let Ok(value) = HeaderValue::from_str(&length.to_string()) {
+            headers.insert(header::CONTENT_LENGTH, value);
+        }
+    }
+
+    if let Some(validators) = validators {
+        validators.apply_to(&mut headers);
+    }
+
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_thumbnail_sizes_accepts_only_the_documented_set() {
+        for &size in &[80, 160, 320, 640, 1080, 2160] {
+            assert!(VALID_THUMBNAIL_SIZES.contains(&size));
+        }
+        for &size in &[0, 79, 81, 500, 2161, u16::MAX] {
+            assert!(!VALID_THUMBNAIL_SIZES.contains(&size));
+        }
+    }
+
+    #[test]
+    fn validators_satisfied_by_matching_etag() {
+        let validators = Validators::new(1, 1, "https://example.com/a.jpg", None);
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_str(&validators.etag).unwrap(),
+        );
+
+        assert!(validators.satisfied_by(&request_headers));
+    }
+
+    #[test]
+    fn validators_satisfied_by_wildcard_etag() {
+        let validators = Validators::new(1, 1, "https://example.com/a.jpg", None);
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("*"));
+
+        assert!(validators.satisfied_by(&request_headers));
+    }
+
+    #[test]
+    fn validators_not_satisfied_by_different_identity() {
+        let validators = Validators::new(1, 1, "https://example.com/a.jpg", None);
+        let other = Validators::new(1, 1, "https://example.com/b.jpg", None);
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_str(&other.etag).unwrap(),
+        );
+
+        assert!(!validators.satisfied_by(&request_headers));
+    }
+
+    #[test]
+    fn validators_satisfied_by_matching_last_modified() {
+        let validators = Validators::new(1, 1, "https://example.com/a.jpg", Some("2020-01-01T00:00:00+00:00"));
+        let last_modified = validators.last_modified.clone().unwrap();
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(header::IF_MODIFIED_SINCE, last_modified);
+
+        assert!(validators.satisfied_by(&request_headers));
+    }
+
+    #[test]
+    fn validators_not_satisfied_without_conditional_headers() {
+        let validators = Validators::new(1, 1, "https://example.com/a.jpg", None);
+        assert!(!validators.satisfied_by(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn parse_range_handles_a_plain_start_end_range() {
+        let range = HeaderValue::from_static("bytes=0-499");
+        assert_eq!(parse_range(&range, 1000), Some((0, 499)));
+    }
+
+    #[test]
+    fn parse_range_handles_an_open_ended_range() {
+        let range = HeaderValue::from_static("bytes=500-");
+        assert_eq!(parse_range(&range, 1000), Some((500, 999)));
+    }
+
+    #[test]
+    fn parse_range_handles_a_suffix_range() {
+        let range = HeaderValue::from_static("bytes=-500");
+        assert_eq!(parse_range(&range, 1000), Some((500, 999)));
+    }
+
+    #[test]
+    fn parse_range_clamps_a_suffix_longer_than_the_resource() {
+        let range = HeaderValue::from_static("bytes=-5000");
+        assert_eq!(parse_range(&range, 1000), Some((0, 999)));
+    }
+
+    #[test]
+    fn parse_range_rejects_a_start_beyond_the_resource() {
+        let range = HeaderValue::from_static("bytes=1000-1500");
+        assert_eq!(parse_range(&range, 1000), None);
+    }
+
+    #[test]
+    fn parse_range_rejects_a_backwards_range() {
+        let range = HeaderValue::from_static("bytes=500-100");
+        assert_eq!(parse_range(&range, 1000), None);
+    }
+
+    #[test]
+    fn parse_range_rejects_a_malformed_unit() {
+        let range = HeaderValue::from_static("items=0-10");
+        assert_eq!(parse_range(&range, 1000), None);
+    }
+
+    #[test]
+    fn blurhash_encode_produces_a_stable_non_empty_hash() {
+        let (width, height) = (4, 4);
+        let rgba = vec![128u8; width as usize * height as usize * 4];
+
+        let hash = blurhash::encode(4, 3, width, height, &rgba);
+
+        assert!(!hash.is_empty());
+        assert_eq!(hash, blurhash::encode(4, 3, width, height, &rgba));
+    }
+
+    #[test]
+    fn fits_in_cache_accepts_images_up_to_the_limit() {
+        assert!(fits_in_cache(100, 100));
+        assert!(fits_in_cache(99, 100));
+        assert!(!fits_in_cache(101, 100));
+    }
+
+    #[test]
+    fn clamp_ttl_secs_passes_through_values_that_fit_in_usize() {
+        assert_eq!(clamp_ttl_secs(86400), 86400);
+    }
+
+    #[test]
+    fn clamp_ttl_secs_saturates_values_too_large_for_usize() {
+        assert_eq!(clamp_ttl_secs(u64::MAX), usize::MAX);
+    }
 }